@@ -1,22 +1,25 @@
 use anyhow::{anyhow, Result};
+use actix_multipart::Multipart;
 use actix_web::web;
 use bincode;
 use futures::StreamExt;
 use lazy_static::lazy_static;
+use mp3lame_encoder;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io::Write;
+use std::io::{Cursor, Write};
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use symphonia::core::audio::SampleBuffer;
-use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::codecs::{CodecType, DecoderOptions};
 use symphonia::core::formats::FormatOptions;
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
 use tokio::io::AsyncReadExt;
+use tokio::sync::Semaphore;
 
 // Constants for directories
 lazy_static! {
@@ -30,6 +33,7 @@ pub struct ImportResult {
     pub file_path: String,
     pub duration_seconds: f64,
     pub cache_key: String,
+    pub format: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -37,6 +41,31 @@ pub struct WaveformData {
     pub peaks: Vec<f32>,
     pub duration: f64,
     pub sample_rate: u32,
+    pub format: String,
+    // Zoom pyramid: `peak_levels[0]` is the finest level, each later level
+    // halves the bin count of the one before it. Every level is interleaved
+    // `[min0, max0, min1, max1, ...]`, so bin `i`'s amplitude range is
+    // `level[i * 2]..=level[i * 2 + 1]`.
+    pub peak_levels: Vec<Vec<f32>>,
+    // Integrated RMS level across the whole track, in dBFS.
+    pub rms_dbfs: f64,
+    // True (sample) peak across the whole track, in dBFS.
+    pub true_peak_dbfs: f64,
+    // Gain that would bring `rms_dbfs` to `LOUDNESS_TARGET_DBFS`, clamped so
+    // applying it never pushes `true_peak_dbfs` above 0 dBFS.
+    pub normalization_gain_db: f64,
+}
+
+// Normalization target used to derive `WaveformData::normalization_gain_db`,
+// in line with common streaming-platform loudness targets.
+const LOUDNESS_TARGET_DBFS: f64 = -14.0;
+
+// Loudness metadata for a track, returned by `GET /api/loudness/{cache_key}`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoudnessInfo {
+    pub rms_dbfs: f64,
+    pub true_peak_dbfs: f64,
+    pub normalization_gain_db: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -45,6 +74,46 @@ pub struct PeakCache {
     pub sample_rate: u32,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Processing,
+    Done,
+    Failed,
+}
+
+// Status of a background import, polled via `GET /api/jobs/{id}`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Job {
+    pub id: String,
+    pub status: JobStatus,
+    pub cache_key: Option<String>,
+    pub error: Option<String>,
+}
+
+// Bins-per-second of the finest pyramid level - much denser than the legacy
+// 50fps `peaks` array so zoomed-in views stay sharp. Bins are cut from each
+// decoded packet's interleaved samples, so the rate is exact only for mono:
+// each extra channel multiplies it, and a packet's short final chunk adds a bin.
+const PYRAMID_BINS_PER_SECOND: usize = 200;
+
+// Stop halving once a level's bin count is at or below this, so the
+// coarsest level stays cheap to send for a fully zoomed-out view.
+const PYRAMID_MIN_LEVEL_BINS: usize = 256;
+
+// Bump this whenever `DiskCacheEntry`'s shape changes so stale on-disk
+// entries from an older binary are recomputed instead of misread.
+const DISK_CACHE_VERSION: u32 = 3;
+
+// What actually gets persisted to `CACHE_DIR/<cache_key>.bin`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DiskCacheEntry {
+    version: u32,
+    waveform: WaveformData,
+    peaks: PeakCache,
+}
+
 // Error type for audio processing
 #[derive(Debug, thiserror::Error)]
 pub enum AudioError {
@@ -64,45 +133,469 @@ pub enum AudioError {
     Processing(String),
 }
 
+// Map a part's declared content-type to a file extension, when it names one
+// of the containers Symphonia can demux.
+fn extension_for_mime(mime: &mime::Mime) -> Option<String> {
+    let ext = match (mime.type_(), mime.subtype().as_str()) {
+        (mime::AUDIO, "mpeg") | (mime::AUDIO, "mp3") => "mp3",
+        (mime::AUDIO, "wav") | (mime::AUDIO, "x-wav") | (mime::AUDIO, "wave") => "wav",
+        (mime::AUDIO, "flac") | (mime::AUDIO, "x-flac") => "flac",
+        (mime::AUDIO, "ogg") | (mime::AUDIO, "vorbis") | (mime::AUDIO, "x-vorbis+ogg") => "ogg",
+        (mime::AUDIO, "aac") | (mime::AUDIO, "mp4") | (mime::AUDIO, "x-m4a") => "m4a",
+        _ => return None,
+    };
+    Some(ext.to_string())
+}
+
+// `generate_cache_key` always produces a lowercase sha256 hex digest; this
+// checks a cache key coming from client input (a URL path segment) has that
+// exact shape before it's allowed anywhere near a filesystem path.
+fn is_valid_cache_key(cache_key: &str) -> bool {
+    cache_key.len() == 64 && cache_key.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+// Convert a linear amplitude (0.0..=1.0 for in-range audio) to dBFS,
+// treating silence as the noise floor instead of -infinity.
+fn amplitude_to_dbfs(amplitude: f64) -> f64 {
+    if amplitude <= 0.0 {
+        return -96.0;
+    }
+    20.0 * amplitude.log10()
+}
+
+// Gain that would bring `rms_dbfs` up to `LOUDNESS_TARGET_DBFS`, clamped so
+// applying it never pushes `true_peak_dbfs` above 0 dBFS.
+fn normalization_gain(rms_dbfs: f64, true_peak_dbfs: f64) -> f64 {
+    let desired_gain = LOUDNESS_TARGET_DBFS - rms_dbfs;
+    let max_gain = -true_peak_dbfs;
+    desired_gain.min(max_gain)
+}
+
+// Short, stable name for a codec, used both as a fallback file extension and
+// as the `format` reported back to clients.
+fn codec_short_name(codec: CodecType) -> &'static str {
+    use symphonia::core::codecs::*;
+    match codec {
+        CODEC_TYPE_MP3 => "mp3",
+        CODEC_TYPE_FLAC => "flac",
+        CODEC_TYPE_VORBIS => "vorbis",
+        CODEC_TYPE_AAC => "aac",
+        CODEC_TYPE_PCM_S16LE | CODEC_TYPE_PCM_S24LE | CODEC_TYPE_PCM_S32LE | CODEC_TYPE_PCM_F32LE => {
+            "pcm"
+        }
+        _ => "unknown",
+    }
+}
+
+// Last-resort container detection when an upload arrives without a usable
+// filename or content-type: probe the leading bytes the same way `analyze_audio`
+// probes a file from disk.
+fn sniff_extension(bytes: &[u8]) -> String {
+    let mss = MediaSourceStream::new(Box::new(Cursor::new(bytes.to_vec())), Default::default());
+
+    let codec = symphonia::default::get_probe()
+        .format(
+            &Hint::new(),
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()
+        .and_then(|probed| probed.format.default_track().map(|t| t.codec_params.codec));
+
+    match codec.map(codec_short_name) {
+        Some("pcm") => "wav".to_string(),
+        Some("vorbis") => "ogg".to_string(),
+        Some("aac") => "m4a".to_string(),
+        Some(other) if other != "unknown" => other.to_string(),
+        _ => "bin".to_string(),
+    }
+}
+
+// A probed file's format reader, decoder, and the track metadata analysis
+// and export both need
+struct ProbedTrack {
+    format: Box<dyn symphonia::core::formats::FormatReader>,
+    decoder: Box<dyn symphonia::core::codecs::Decoder>,
+    sample_rate: u32,
+    channels: u16,
+    duration: Option<f64>,
+    codec: CodecType,
+}
+
+// Open and probe a file, building a decoder for its default track. This is
+// the one place that knows how to go from a path to a decodable track, shared
+// by `analyze_audio` and the MP3 export path.
+fn probe_file(file_path: &Path) -> Result<ProbedTrack, AudioError> {
+    let file = File::open(file_path)?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = file_path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| AudioError::Symphonia(e.to_string()))?;
+
+    let track = probed.format.default_track().ok_or_else(|| {
+        AudioError::InvalidAudioFile("No default track found".to_string())
+    })?;
+
+    let sample_rate = track.codec_params.sample_rate.ok_or_else(|| {
+        AudioError::InvalidAudioFile("Could not determine sample rate".to_string())
+    })?;
+    let channels = track
+        .codec_params
+        .channels
+        .map(|c| c.count() as u16)
+        .unwrap_or(2);
+    let duration = track
+        .codec_params
+        .n_frames
+        .map(|frames| frames as f64 / sample_rate as f64);
+    let codec = track.codec_params.codec;
+
+    let decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| AudioError::Symphonia(e.to_string()))?;
+
+    Ok(ProbedTrack {
+        format: probed.format,
+        decoder,
+        sample_rate,
+        channels,
+        duration,
+        codec,
+    })
+}
+
+// Walk every packet of `format`, decoding it and handing the interleaved
+// samples to `on_samples`. Shared by waveform analysis and MP3 export so
+// there is only one place that knows how to drive a Symphonia decoder.
+fn decode_packets<F>(
+    mut format: Box<dyn symphonia::core::formats::FormatReader>,
+    decoder: &mut Box<dyn symphonia::core::codecs::Decoder>,
+    mut on_samples: F,
+) -> Result<(), AudioError>
+where
+    F: FnMut(&[f32]),
+{
+    let mut sample_buffer = None;
+
+    while let Ok(packet) = format.next_packet() {
+        let decoded = decoder
+            .decode(&packet)
+            .map_err(|e| AudioError::Symphonia(format!("Decode error: {}", e)))?;
+
+        let spec = decoded.spec();
+
+        let buffer = match &mut sample_buffer {
+            Some(buffer) => buffer,
+            None => {
+                let new_buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, *spec);
+                sample_buffer = Some(new_buffer);
+                sample_buffer.as_mut().unwrap()
+            }
+        };
+
+        buffer.copy_interleaved_ref(decoded);
+        on_samples(buffer.samples());
+    }
+
+    Ok(())
+}
+
+// Decode an entire file to interleaved `f32` PCM, for feeding to the MP3 encoder
+fn decode_full(file_path: &Path) -> Result<(Vec<f32>, u16, u32), AudioError> {
+    let mut probed = probe_file(file_path)?;
+
+    let mut samples = Vec::new();
+    decode_packets(probed.format, &mut probed.decoder, |chunk| {
+        samples.extend_from_slice(chunk)
+    })?;
+
+    Ok((samples, probed.channels, probed.sample_rate))
+}
+
+// CBR vs VBR selection for `POST /api/export/{cache_key}`
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportMode {
+    Cbr,
+    Vbr,
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(default)]
+pub struct ExportOptions {
+    pub mode: Option<ExportMode>,
+    // CBR bitrate in kbps (default 192). Ignored when `mode` is `Vbr`.
+    pub bitrate_kbps: Option<u32>,
+    // LAME VBR quality, 0 (best) - 9 (smallest). Ignored when `mode` is `Cbr`.
+    pub vbr_quality: Option<u8>,
+}
+
+// Round a requested bitrate down to the nearest bitrate LAME supports
+fn bitrate_from_kbps(kbps: u32) -> mp3lame_encoder::Bitrate {
+    use mp3lame_encoder::Bitrate;
+    match kbps {
+        0..=8 => Bitrate::Kbps8,
+        9..=16 => Bitrate::Kbps16,
+        17..=24 => Bitrate::Kbps24,
+        25..=32 => Bitrate::Kbps32,
+        33..=40 => Bitrate::Kbps40,
+        41..=48 => Bitrate::Kbps48,
+        49..=64 => Bitrate::Kbps64,
+        65..=80 => Bitrate::Kbps80,
+        81..=96 => Bitrate::Kbps96,
+        97..=112 => Bitrate::Kbps112,
+        113..=128 => Bitrate::Kbps128,
+        129..=160 => Bitrate::Kbps160,
+        161..=192 => Bitrate::Kbps192,
+        193..=224 => Bitrate::Kbps224,
+        225..=256 => Bitrate::Kbps256,
+        _ => Bitrate::Kbps320,
+    }
+}
+
+// Map LAME's 0 (best) - 9 (worst) VBR quality scale onto the encoder's enum
+fn quality_from_u8(level: u8) -> mp3lame_encoder::Quality {
+    use mp3lame_encoder::Quality;
+    match level.min(9) {
+        0 => Quality::Best,
+        1 => Quality::NearBest,
+        2 => Quality::VeryGood,
+        3 => Quality::Good,
+        4 => Quality::Decent,
+        5 => Quality::Ok,
+        6 => Quality::SecondMediocre,
+        7 => Quality::Mediocre,
+        8 => Quality::SecondWorst,
+        _ => Quality::Worst,
+    }
+}
+
+// Encode interleaved `f32` PCM to MP3 via LAME, in either CBR or VBR mode
+fn encode_mp3(
+    samples: &[f32],
+    channels: u16,
+    sample_rate: u32,
+    options: &ExportOptions,
+) -> Result<Vec<u8>, AudioError> {
+    use mp3lame_encoder::{Builder, FlushNoGap, InterleavedPcm, Quality};
+
+    let mut builder = Builder::new()
+        .ok_or_else(|| AudioError::Processing("Failed to create LAME encoder".to_string()))?;
+
+    builder
+        .set_num_channels(channels as u8)
+        .map_err(|e| AudioError::Processing(format!("Invalid channel count: {:?}", e)))?;
+    builder
+        .set_sample_rate(sample_rate)
+        .map_err(|e| AudioError::Processing(format!("Invalid sample rate: {:?}", e)))?;
+
+    match options.mode {
+        Some(ExportMode::Vbr) => {
+            let quality = options.vbr_quality.map(quality_from_u8).unwrap_or(Quality::Good);
+            builder
+                .set_quality(quality)
+                .map_err(|e| AudioError::Processing(format!("Invalid VBR quality: {:?}", e)))?;
+            // Leaving `brate` unset lets LAME pick a bitrate per frame.
+        }
+        _ => {
+            builder
+                .set_brate(bitrate_from_kbps(options.bitrate_kbps.unwrap_or(192)))
+                .map_err(|e| AudioError::Processing(format!("Invalid bitrate: {:?}", e)))?;
+            builder
+                .set_quality(Quality::Best)
+                .map_err(|e| AudioError::Processing(format!("Invalid quality: {:?}", e)))?;
+        }
+    }
+
+    let mut encoder = builder
+        .build()
+        .map_err(|e| AudioError::Processing(format!("Failed to initialize LAME encoder: {:?}", e)))?;
+
+    let input = InterleavedPcm(samples);
+    let mut mp3_buffer = Vec::with_capacity(mp3lame_encoder::max_required_buffer_size(samples.len()));
+
+    let encoded = encoder
+        .encode(input, mp3_buffer.spare_capacity_mut())
+        .map_err(|e| AudioError::Processing(format!("MP3 encode error: {:?}", e)))?;
+    unsafe {
+        mp3_buffer.set_len(mp3_buffer.len() + encoded);
+    }
+
+    let flushed = encoder
+        .flush::<FlushNoGap>(mp3_buffer.spare_capacity_mut())
+        .map_err(|e| AudioError::Processing(format!("MP3 flush error: {:?}", e)))?;
+    unsafe {
+        mp3_buffer.set_len(mp3_buffer.len() + flushed);
+    }
+
+    Ok(mp3_buffer)
+}
+
 // Main audio processor struct
 pub struct AudioProcessor {
     cache: Mutex<HashMap<String, PeakCache>>,
     waveform_cache: Mutex<HashMap<String, WaveformData>>,
+    file_paths: Mutex<HashMap<String, PathBuf>>,
+    jobs: Mutex<HashMap<String, Job>>,
+    // Bounds how many imports decode concurrently; each lookup/status map
+    // above has its own lock so polling a job never waits behind a decode.
+    import_semaphore: Arc<Semaphore>,
 }
 
 impl AudioProcessor {
-    pub fn new() -> Self {
+    pub fn new(max_concurrent_imports: usize) -> Self {
         Self {
             cache: Mutex::new(HashMap::new()),
             waveform_cache: Mutex::new(HashMap::new()),
+            file_paths: Mutex::new(HashMap::new()),
+            jobs: Mutex::new(HashMap::new()),
+            import_semaphore: Arc::new(Semaphore::new(max_concurrent_imports.max(1))),
         }
     }
 
     // Process an uploaded audio file
+    //
+    // Takes `Arc<Self>` (rather than `&self`) so the spawned decode task
+    // below can hold its own handle to the processor after this method returns.
     pub async fn process_upload(
-        &mut self,
-        mut payload: actix_web::web::Payload,
-    ) -> Result<ImportResult, AudioError> {
+        self: Arc<Self>,
+        mut payload: Multipart,
+    ) -> Result<String, AudioError> {
         // Create uploads directory if it doesn't exist
         fs::create_dir_all(*UPLOAD_DIR)?;
-        
-        // Generate a unique filename
-        let file_name = format!("{}.wav", uuid::Uuid::new_v4());
+
+        let mut bytes = Vec::new();
+        let mut declared_extension: Option<String> = None;
+        let mut found_file_part = false;
+
+        // Walk the multipart parts. A real editor UI can send other fields
+        // alongside the file (e.g. a `title`/`track_id` field), so only the
+        // part whose `Content-Disposition` carries a filename is treated as
+        // the file; every other part is drained and discarded so its bytes
+        // never leak into the audio buffer.
+        while let Some(field) = payload.next().await {
+            let mut field =
+                field.map_err(|e| AudioError::Processing(format!("Malformed upload: {}", e)))?;
+
+            let is_file_part = field.content_disposition().get_filename().is_some();
+
+            if !is_file_part || found_file_part {
+                while let Some(chunk) = field.next().await {
+                    chunk.map_err(|e| AudioError::Processing(e.to_string()))?;
+                }
+                continue;
+            }
+            found_file_part = true;
+
+            declared_extension = field
+                .content_disposition()
+                .get_filename()
+                .and_then(|name| Path::new(name).extension())
+                .and_then(|ext| ext.to_str())
+                .map(|ext| ext.to_lowercase())
+                .or_else(|| field.content_type().and_then(extension_for_mime));
+
+            while let Some(chunk) = field.next().await {
+                let chunk = chunk.map_err(|e| AudioError::Processing(e.to_string()))?;
+                bytes.extend_from_slice(&chunk);
+            }
+        }
+
+        if bytes.is_empty() {
+            return Err(AudioError::InvalidAudioFile(
+                "No file data received".to_string(),
+            ));
+        }
+
+        // Neither the filename nor the content-type gave us an extension, so
+        // fall back to sniffing the container from the leading bytes.
+        let extension = declared_extension.unwrap_or_else(|| sniff_extension(&bytes));
+
+        let file_name = format!("{}.{}", uuid::Uuid::new_v4(), extension);
         let file_path = Path::new(*UPLOAD_DIR).join(&file_name);
-        
+
         // Save the uploaded file
         let mut file = File::create(&file_path)?;
-        let mut bytes = Vec::new();
-        
-        while let Some(chunk) = payload.next().await {
-            let chunk = chunk.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-            bytes.extend_from_slice(&chunk);
-        }
-        
         file.write_all(&bytes)?;
-        
-        // Process the audio file
-        self.analyze_audio(&file_path).await
+
+        // Hand the decode off to a background task and report a job id
+        // immediately, so one large upload doesn't hold up every other request.
+        let job_id = uuid::Uuid::new_v4().to_string();
+        self.jobs.lock().unwrap().insert(
+            job_id.clone(),
+            Job {
+                id: job_id.clone(),
+                status: JobStatus::Queued,
+                cache_key: None,
+                error: None,
+            },
+        );
+
+        let processor = Arc::clone(&self);
+        let job_id_for_task = job_id.clone();
+        tokio::spawn(async move {
+            processor.run_import_job(job_id_for_task, file_path).await;
+        });
+
+        Ok(job_id)
+    }
+
+    // Decode a saved upload under the concurrency limit and record the
+    // outcome against its job id
+    async fn run_import_job(&self, job_id: String, file_path: PathBuf) {
+        let _permit = match self.import_semaphore.acquire().await {
+            Ok(permit) => permit,
+            Err(_) => return, // Semaphore closed, e.g. during shutdown
+        };
+
+        self.set_job_status(&job_id, JobStatus::Processing, None, None);
+
+        match self.analyze_audio(&file_path).await {
+            Ok(result) => {
+                self.set_job_status(&job_id, JobStatus::Done, Some(result.cache_key), None)
+            }
+            Err(e) => self.set_job_status(&job_id, JobStatus::Failed, None, Some(e.to_string())),
+        }
+    }
+
+    fn set_job_status(
+        &self,
+        job_id: &str,
+        status: JobStatus,
+        cache_key: Option<String>,
+        error: Option<String>,
+    ) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(job_id) {
+            job.status = status;
+            if cache_key.is_some() {
+                job.cache_key = cache_key;
+            }
+            if error.is_some() {
+                job.error = error;
+            }
+        }
+    }
+
+    // Get the status of a background import job
+    pub fn get_job(&self, job_id: &str) -> Result<Job, AudioError> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(job_id)
+            .cloned()
+            .ok_or_else(|| AudioError::Cache("Job not found".to_string()))
     }
     
     // Analyze audio file and generate waveform data
@@ -114,112 +607,148 @@ impl AudioProcessor {
         
         // Generate cache key from file content
         let cache_key = self.generate_cache_key(file_path).await?;
-        
-        // Check if we already have this file in cache
+
+        // Remember where the source file lives so it can be streamed back later
+        self.file_paths
+            .lock()
+            .unwrap()
+            .insert(cache_key.clone(), file_path.to_path_buf());
+
+        // Check if we already have this file in memory
         if self.waveform_cache.lock().unwrap().contains_key(&cache_key) {
             let waveform = self.waveform_cache.lock().unwrap().get(&cache_key).unwrap().clone();
             return Ok(ImportResult {
                 file_path: file_path.to_string_lossy().to_string(),
                 duration_seconds: waveform.duration,
                 cache_key,
+                format: waveform.format,
             });
         }
-        
-        // Open the audio file
-        let file = File::open(file_path)?;
-        let mss = MediaSourceStream::new(Box::new(file), Default::default());
-        
-        // Create a probe to detect the format
-        let mut hint = Hint::new();
-        if let Some(ext) = file_path.extension().and_then(|e| e.to_str()) {
-            hint.with_extension(ext);
+
+        // Not in memory - fall back to the on-disk cache before re-decoding
+        if let Some(entry) = self.load_disk_cache(&cache_key) {
+            self.waveform_cache
+                .lock()
+                .unwrap()
+                .insert(cache_key.clone(), entry.waveform.clone());
+            self.cache
+                .lock()
+                .unwrap()
+                .insert(cache_key.clone(), entry.peaks);
+            return Ok(ImportResult {
+                file_path: file_path.to_string_lossy().to_string(),
+                duration_seconds: entry.waveform.duration,
+                cache_key,
+                format: entry.waveform.format,
+            });
         }
-        
-        let format_opts = FormatOptions::default();
-        let metadata_opts = MetadataOptions::default();
-        let decoder_opts = DecoderOptions::default();
-        
-        // Probe the audio file
-        let probed = symphonia::default::get_probe()
-            .format(&hint, mss, &format_opts, &metadata_opts)
-            .map_err(|e| AudioError::Symphonia(e.to_string()))?;
-        
-        // Get the default track
-        let track = probed.format.default_track().ok_or_else(|| {
-            AudioError::InvalidAudioFile("No default track found".to_string())
+
+        // Open and probe the file, and build a decoder for its default track
+        let mut probed = probe_file(file_path)?;
+        let duration = probed.duration.ok_or_else(|| {
+            AudioError::InvalidAudioFile("Could not determine duration".to_string())
         })?;
-        
-        // Create a decoder
-        let mut decoder = symphonia::default::get_codecs()
-            .make(&track.codec_params, &decoder_opts)
-            .map_err(|e| AudioError::Symphonia(e.to_string()))?;
-        
-        // Get the sample rate and duration
-        let sample_rate = track
-            .codec_params
-            .sample_rate
-            .ok_or_else(|| AudioError::InvalidAudioFile("Could not determine sample rate".to_string()))?;
-            
-        let duration = track
-            .codec_params
-            .n_frames
-            .map(|frames| frames as f64 / sample_rate as f64)
-            .ok_or_else(|| AudioError::InvalidAudioFile("Could not determine duration".to_string()))?;
-        
+
         // Process the audio to generate waveform data
-        let mut waveform_data = self.process_audio_frames(probed.format, &mut decoder, sample_rate)?;
+        let mut waveform_data =
+            self.process_audio_frames(probed.format, &mut probed.decoder, probed.sample_rate)?;
         waveform_data.duration = duration;
-        
-        // Cache the results
+        waveform_data.format = codec_short_name(probed.codec).to_string();
+
+        let peak_cache = PeakCache {
+            peaks: waveform_data.peaks.clone(),
+            sample_rate: waveform_data.sample_rate,
+        };
+
+        // Cache the results in memory and on disk
         self.waveform_cache
             .lock()
             .unwrap()
-            .insert(cache_key.clone(), waveform_data);
-        
+            .insert(cache_key.clone(), waveform_data.clone());
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(cache_key.clone(), peak_cache.clone());
+        self.write_disk_cache(&cache_key, &waveform_data, &peak_cache)?;
+
         Ok(ImportResult {
             file_path: file_path.to_string_lossy().to_string(),
             duration_seconds: duration,
             cache_key,
+            format: waveform_data.format,
         })
     }
+
+    // Path of the on-disk cache entry for a given cache key, or `None` if
+    // `cache_key` isn't a sha256 hex digest (the only shape
+    // `generate_cache_key` ever produces). Rejecting anything else here
+    // keeps a client-supplied `cache_key` (e.g. via `GET
+    // /api/waveform/{cache_key}`) from ever reaching `fs::read`/`fs::write`
+    // as a path-traversal payload.
+    fn disk_cache_path(cache_key: &str) -> Option<PathBuf> {
+        if !is_valid_cache_key(cache_key) {
+            return None;
+        }
+        Some(Path::new(*CACHE_DIR).join(format!("{}.bin", cache_key)))
+    }
+
+    // Persist a decoded file's waveform/peaks to `CACHE_DIR` so a restart
+    // doesn't have to re-decode it
+    fn write_disk_cache(
+        &self,
+        cache_key: &str,
+        waveform: &WaveformData,
+        peaks: &PeakCache,
+    ) -> Result<(), AudioError> {
+        let path = Self::disk_cache_path(cache_key)
+            .ok_or_else(|| AudioError::Cache("Invalid cache key".to_string()))?;
+        fs::create_dir_all(*CACHE_DIR)?;
+
+        let entry = DiskCacheEntry {
+            version: DISK_CACHE_VERSION,
+            waveform: waveform.clone(),
+            peaks: peaks.clone(),
+        };
+
+        let bytes = bincode::serialize(&entry)
+            .map_err(|e| AudioError::Cache(format!("Failed to serialize cache entry: {}", e)))?;
+        fs::write(path, bytes)?;
+
+        Ok(())
+    }
+
+    // Load a cache entry from disk, discarding it if it was written by an
+    // older, incompatible version of the cache format
+    fn load_disk_cache(&self, cache_key: &str) -> Option<DiskCacheEntry> {
+        let path = Self::disk_cache_path(cache_key)?;
+        let bytes = fs::read(path).ok()?;
+        let entry: DiskCacheEntry = bincode::deserialize(&bytes).ok()?;
+
+        if entry.version != DISK_CACHE_VERSION {
+            return None;
+        }
+
+        Some(entry)
+    }
     
     // Process audio frames to generate waveform data
     fn process_audio_frames(
         &self,
-        mut format: Box<dyn symphonia::core::formats::FormatReader>,
+        format: Box<dyn symphonia::core::formats::FormatReader>,
         decoder: &mut Box<dyn symphonia::core::codecs::Decoder>,
         sample_rate: u32,
     ) -> Result<WaveformData, AudioError> {
         let mut peaks = Vec::new();
-        let mut sample_buffer = None;
-        
-        // Process each packet in the audio file
-        while let Ok(packet) = format.next_packet() {
-            // Decode the packet
-            let decoded = decoder.decode(&packet).map_err(|e| {
-                AudioError::Symphonia(format!("Decode error: {}", e))
-            })?;
-            
-            // Get the decoded audio buffer
-            let spec = decoded.spec();
-            
-            // Create or reuse a sample buffer
-            let buffer = match &mut sample_buffer {
-                Some(buffer) => buffer,
-                None => {
-                    let new_buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, *spec);
-                    sample_buffer = Some(new_buffer);
-                    sample_buffer.as_mut().unwrap()
-                }
-            };
-            
-            // Copy the decoded samples to the sample buffer
-            buffer.copy_interleaved_ref(decoded);
-            
-            // Process the samples to generate peaks
-            let samples = buffer.samples();
-            let chunk_size = sample_rate as usize / 50; // 50 FPS for waveform
-            
+        let mut finest_level = Vec::new();
+        let mut sum_sq = 0.0f64;
+        let mut sample_count = 0u64;
+        let mut true_peak = 0.0f32;
+
+        let chunk_size = (sample_rate as usize / 50).max(1); // 50 FPS for waveform
+        // Finer than the legacy 50fps `peaks`, this is the base of the zoom pyramid.
+        let pyramid_chunk_size = (sample_rate as usize / PYRAMID_BINS_PER_SECOND).max(1);
+
+        decode_packets(format, decoder, |samples| {
             for chunk in samples.chunks(chunk_size) {
                 let peak = chunk
                     .iter()
@@ -227,12 +756,39 @@ impl AudioProcessor {
                     .fold(0.0f32, |a, b| a.max(b));
                 peaks.push(peak);
             }
-        }
-        
+
+            for chunk in samples.chunks(pyramid_chunk_size) {
+                let min = chunk.iter().cloned().fold(f32::INFINITY, f32::min);
+                let max = chunk.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+                finest_level.push(min);
+                finest_level.push(max);
+            }
+
+            for &sample in samples {
+                sum_sq += (sample as f64) * (sample as f64);
+                true_peak = true_peak.max(sample.abs());
+            }
+            sample_count += samples.len() as u64;
+        })?;
+
+        let rms = if sample_count > 0 {
+            (sum_sq / sample_count as f64).sqrt()
+        } else {
+            0.0
+        };
+        let rms_dbfs = amplitude_to_dbfs(rms);
+        let true_peak_dbfs = amplitude_to_dbfs(true_peak as f64);
+        let normalization_gain_db = normalization_gain(rms_dbfs, true_peak_dbfs);
+
         Ok(WaveformData {
             peaks,
             duration: 0.0, // Will be set by the caller
             sample_rate,
+            format: String::new(), // Will be set by the caller
+            peak_levels: build_peak_pyramid(finest_level),
+            rms_dbfs,
+            true_peak_dbfs,
+            normalization_gain_db,
         })
     }
     
@@ -251,23 +807,383 @@ impl AudioProcessor {
         Ok(hex::encode(result))
     }
     
-    // Get waveform data for a cache key
+    // Get waveform data for a cache key, falling back to the on-disk cache
+    // before giving up
     pub async fn get_waveform(&self, cache_key: &str) -> Result<WaveformData, AudioError> {
+        if let Some(waveform) = self.waveform_cache.lock().unwrap().get(cache_key).cloned() {
+            return Ok(waveform);
+        }
+
+        let entry = self
+            .load_disk_cache(cache_key)
+            .ok_or_else(|| AudioError::Cache("Waveform data not found in cache".to_string()))?;
+
         self.waveform_cache
             .lock()
             .unwrap()
-            .get(cache_key)
-            .cloned()
-            .ok_or_else(|| AudioError::Cache("Waveform data not found in cache".to_string()))
+            .insert(cache_key.to_string(), entry.waveform.clone());
+
+        Ok(entry.waveform)
     }
-    
-    // Get peaks for a cache key
+
+    // Get peaks for a cache key, falling back to the on-disk cache before
+    // giving up
     pub async fn get_peaks(&self, cache_key: &str) -> Result<PeakCache, AudioError> {
+        if let Some(peaks) = self.cache.lock().unwrap().get(cache_key).cloned() {
+            return Ok(peaks);
+        }
+
+        let entry = self
+            .load_disk_cache(cache_key)
+            .ok_or_else(|| AudioError::Cache("Peak data not found in cache".to_string()))?;
+
         self.cache
+            .lock()
+            .unwrap()
+            .insert(cache_key.to_string(), entry.peaks.clone());
+
+        Ok(entry.peaks)
+    }
+
+    // Get loudness metadata for a cache key, falling back to the on-disk
+    // cache before giving up
+    pub async fn get_loudness(&self, cache_key: &str) -> Result<LoudnessInfo, AudioError> {
+        let waveform = self.get_waveform(cache_key).await?;
+
+        Ok(LoudnessInfo {
+            rms_dbfs: waveform.rms_dbfs,
+            true_peak_dbfs: waveform.true_peak_dbfs,
+            normalization_gain_db: waveform.normalization_gain_db,
+        })
+    }
+
+    // Path of the stored source file for a cache key, used to stream it back
+    // for playback
+    pub fn get_file_path(&self, cache_key: &str) -> Result<PathBuf, AudioError> {
+        self.file_paths
             .lock()
             .unwrap()
             .get(cache_key)
             .cloned()
-            .ok_or_else(|| AudioError::Cache("Peak data not found in cache".to_string()))
+            .ok_or_else(|| AudioError::Cache("File path not found in cache".to_string()))
+    }
+
+    // Re-decode the cached source for a cache key and encode it to MP3.
+    // Decode and encode both run on a blocking task since neither is async;
+    // this keeps the worker thread free for other requests in the meantime.
+    pub async fn export_mp3(
+        &self,
+        cache_key: &str,
+        options: ExportOptions,
+    ) -> Result<Vec<u8>, AudioError> {
+        let file_path = self.get_file_path(cache_key)?;
+
+        let (samples, channels, sample_rate) =
+            tokio::task::spawn_blocking(move || decode_full(&file_path))
+                .await
+                .map_err(|e| AudioError::Processing(format!("Decode task panicked: {}", e)))??;
+
+        tokio::task::spawn_blocking(move || encode_mp3(&samples, channels, sample_rate, &options))
+            .await
+            .map_err(|e| AudioError::Processing(format!("Encode task panicked: {}", e)))?
+    }
+}
+
+// Build a zoom pyramid from the finest interleaved min/max level, repeatedly
+// halving by taking the min/max of adjacent bin pairs until a level fits in
+// `PYRAMID_MIN_LEVEL_BINS` bins (or can't be halved any further).
+fn build_peak_pyramid(finest: Vec<f32>) -> Vec<Vec<f32>> {
+    let mut levels = vec![finest];
+
+    loop {
+        let current = levels.last().unwrap();
+        let bin_count = current.len() / 2;
+        if bin_count <= PYRAMID_MIN_LEVEL_BINS || bin_count <= 1 {
+            break;
+        }
+
+        let next: Vec<f32> = current
+            .chunks(4)
+            .map(|pair| {
+                let min = pair.iter().step_by(2).cloned().fold(f32::INFINITY, f32::min);
+                let max = pair
+                    .iter()
+                    .skip(1)
+                    .step_by(2)
+                    .cloned()
+                    .fold(f32::NEG_INFINITY, f32::max);
+                [min, max]
+            })
+            .flatten()
+            .collect();
+        levels.push(next);
+    }
+
+    levels
+}
+
+// Pick the coarsest pyramid level whose bin count over `[start, end)` is
+// still >= `width`, then slice out that window. `width == 0` always picks
+// the coarsest level (a fully zoomed-out view).
+pub fn slice_peak_pyramid(waveform: &WaveformData, start: f64, end: f64, width: usize) -> Vec<f32> {
+    if waveform.peak_levels.is_empty() || waveform.duration <= 0.0 {
+        return Vec::new();
+    }
+
+    let start = start.max(0.0);
+    let end = end.max(start);
+    let window = (end - start).max(f64::EPSILON);
+
+    let level = waveform
+        .peak_levels
+        .iter()
+        .rev()
+        .find(|level| {
+            let bin_count = level.len() / 2;
+            let bins_in_window = (window / waveform.duration * bin_count as f64).ceil() as usize;
+            bins_in_window >= width
+        })
+        .unwrap_or(&waveform.peak_levels[0]);
+
+    let bin_count = level.len() / 2;
+    if bin_count == 0 {
+        return Vec::new();
+    }
+    let bin_duration = waveform.duration / bin_count as f64;
+
+    let first_bin = ((start / bin_duration).floor() as usize).min(bin_count - 1);
+    let last_bin = ((end / bin_duration).ceil() as usize)
+        .max(first_bin + 1)
+        .min(bin_count);
+
+    level[first_bin * 2..last_bin * 2].to_vec()
+}
+
+// Map a decoded codec's short name to the MIME type used when streaming the
+// cached source file back to a client
+pub fn content_type_for_format(format: &str) -> &'static str {
+    match format {
+        "mp3" => "audio/mpeg",
+        "flac" => "audio/flac",
+        "vorbis" => "audio/ogg",
+        "aac" => "audio/aac",
+        "pcm" => "audio/wav",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod pyramid_tests {
+    use super::*;
+
+    fn waveform_with_levels(peak_levels: Vec<Vec<f32>>, duration: f64) -> WaveformData {
+        WaveformData {
+            peaks: Vec::new(),
+            duration,
+            sample_rate: 44100,
+            format: "pcm".to_string(),
+            peak_levels,
+            rms_dbfs: -96.0,
+            true_peak_dbfs: -96.0,
+            normalization_gain_db: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_build_peak_pyramid_halves_until_min_bins() {
+        let finest: Vec<f32> = (0..2048).map(|i| (i % 2) as f32).collect();
+        let levels = build_peak_pyramid(finest.clone());
+
+        assert_eq!(levels[0], finest);
+        assert!(levels.len() > 1);
+        for pair in levels.windows(2) {
+            assert_eq!(pair[0].len() / 2, pair[1].len());
+        }
+        assert!(levels.last().unwrap().len() / 2 <= PYRAMID_MIN_LEVEL_BINS);
+    }
+
+    #[test]
+    fn test_build_peak_pyramid_empty_input() {
+        let levels = build_peak_pyramid(Vec::new());
+        assert_eq!(levels, vec![Vec::<f32>::new()]);
+    }
+
+    #[test]
+    fn test_slice_peak_pyramid_empty_levels_returns_empty() {
+        let waveform = waveform_with_levels(Vec::new(), 10.0);
+        assert_eq!(slice_peak_pyramid(&waveform, 0.0, 10.0, 100), Vec::new());
+    }
+
+    #[test]
+    fn test_slice_peak_pyramid_zero_duration_returns_empty() {
+        let waveform = waveform_with_levels(vec![vec![0.0, 1.0]], 0.0);
+        assert_eq!(slice_peak_pyramid(&waveform, 0.0, 1.0, 10), Vec::new());
+    }
+
+    #[test]
+    fn test_slice_peak_pyramid_does_not_panic_on_empty_finest_level() {
+        // Reproduces a track whose metadata reports a nonzero duration but
+        // whose decode never yielded a sample (truncated/malformed input).
+        let waveform = waveform_with_levels(build_peak_pyramid(Vec::new()), 12.0);
+        assert_eq!(slice_peak_pyramid(&waveform, 0.0, 12.0, 0), Vec::new());
+    }
+
+    #[test]
+    fn test_slice_peak_pyramid_full_window_returns_coarsest_level() {
+        let finest: Vec<f32> = (0..1024).map(|i| (i % 2) as f32).collect();
+        let levels = build_peak_pyramid(finest);
+        let coarsest = levels.last().unwrap().clone();
+        let waveform = waveform_with_levels(levels, 10.0);
+
+        assert_eq!(slice_peak_pyramid(&waveform, 0.0, 10.0, 0), coarsest);
+    }
+}
+
+#[cfg(test)]
+mod disk_cache_tests {
+    use super::*;
+
+    fn sample_waveform() -> WaveformData {
+        WaveformData {
+            peaks: vec![0.1, 0.2],
+            duration: 1.0,
+            sample_rate: 44100,
+            format: "pcm".to_string(),
+            peak_levels: vec![vec![0.0, 0.1]],
+            rms_dbfs: -20.0,
+            true_peak_dbfs: -6.0,
+            normalization_gain_db: -2.0,
+        }
+    }
+
+    fn sample_peaks() -> PeakCache {
+        PeakCache {
+            peaks: vec![0.1, 0.2],
+            sample_rate: 44100,
+        }
+    }
+
+    #[test]
+    fn test_write_then_load_disk_cache_round_trips() {
+        let processor = AudioProcessor::new(1);
+        let cache_key = "b".repeat(64);
+        let waveform = sample_waveform();
+        let peaks = sample_peaks();
+
+        processor
+            .write_disk_cache(&cache_key, &waveform, &peaks)
+            .expect("writing a valid cache key should succeed");
+
+        let entry = processor
+            .load_disk_cache(&cache_key)
+            .expect("entry should round-trip");
+        assert_eq!(entry.version, DISK_CACHE_VERSION);
+        assert_eq!(entry.waveform.sample_rate, waveform.sample_rate);
+
+        let _ = fs::remove_file(AudioProcessor::disk_cache_path(&cache_key).unwrap());
+    }
+
+    #[test]
+    fn test_load_disk_cache_rejects_stale_version() {
+        let processor = AudioProcessor::new(1);
+        let cache_key = "a".repeat(64);
+
+        // Write an entry stamped with an outdated version, bypassing
+        // `write_disk_cache` (which always stamps the current version), to
+        // simulate a cache file left behind by an older binary.
+        let stale_entry = DiskCacheEntry {
+            version: DISK_CACHE_VERSION - 1,
+            waveform: sample_waveform(),
+            peaks: sample_peaks(),
+        };
+        let path = AudioProcessor::disk_cache_path(&cache_key).unwrap();
+        fs::create_dir_all(*CACHE_DIR).unwrap();
+        fs::write(&path, bincode::serialize(&stale_entry).unwrap()).unwrap();
+
+        assert!(processor.load_disk_cache(&cache_key).is_none());
+
+        let _ = fs::remove_file(&path);
+    }
+}
+
+#[cfg(test)]
+mod lame_mapping_tests {
+    use super::*;
+    use mp3lame_encoder::{Bitrate, Quality};
+
+    #[test]
+    fn test_bitrate_from_kbps_clamps_below_lowest_step() {
+        assert!(matches!(bitrate_from_kbps(0), Bitrate::Kbps8));
+    }
+
+    #[test]
+    fn test_bitrate_from_kbps_maps_step_boundaries() {
+        assert!(matches!(bitrate_from_kbps(8), Bitrate::Kbps8));
+        assert!(matches!(bitrate_from_kbps(9), Bitrate::Kbps16));
+        assert!(matches!(bitrate_from_kbps(192), Bitrate::Kbps192));
+        assert!(matches!(bitrate_from_kbps(193), Bitrate::Kbps224));
+    }
+
+    #[test]
+    fn test_bitrate_from_kbps_clamps_above_highest_step() {
+        assert!(matches!(bitrate_from_kbps(320), Bitrate::Kbps320));
+        assert!(matches!(bitrate_from_kbps(u32::MAX), Bitrate::Kbps320));
+    }
+
+    #[test]
+    fn test_quality_from_u8_maps_endpoints() {
+        assert!(matches!(quality_from_u8(0), Quality::Best));
+        assert!(matches!(quality_from_u8(9), Quality::Worst));
+    }
+
+    #[test]
+    fn test_quality_from_u8_clamps_above_scale() {
+        assert!(matches!(quality_from_u8(255), Quality::Worst));
+    }
+}
+
+#[cfg(test)]
+mod loudness_tests {
+    use super::*;
+
+    #[test]
+    fn test_amplitude_to_dbfs_silence_is_floor() {
+        assert_eq!(amplitude_to_dbfs(0.0), -96.0);
+    }
+
+    #[test]
+    fn test_amplitude_to_dbfs_full_scale_is_zero() {
+        assert!((amplitude_to_dbfs(1.0) - 0.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_amplitude_to_dbfs_half_scale() {
+        // -6.02 dBFS, the standard value for a halving of amplitude.
+        assert!((amplitude_to_dbfs(0.5) - (-6.0206)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_normalization_gain_boosts_quiet_track_within_headroom() {
+        // -30 dBFS RMS with plenty of headroom before the -20 dBFS true
+        // peak: gain should bring RMS all the way up to the -14 dBFS target.
+        let gain = normalization_gain(-30.0, -20.0);
+        assert!((gain - 16.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_normalization_gain_clamps_against_true_peak() {
+        // RMS is quiet but the track already peaks at -1 dBFS, so boosting
+        // all the way to -14 dBFS RMS would clip; gain must be capped at
+        // the 1 dB of headroom under the peak.
+        let gain = normalization_gain(-40.0, -1.0);
+        assert!((gain - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_normalization_gain_is_negative_for_already_loud_track() {
+        // A track already louder than the target should get a gain
+        // reduction, not a boost.
+        let gain = normalization_gain(-6.0, -0.5);
+        assert!(gain < 0.0);
     }
 }
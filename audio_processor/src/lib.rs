@@ -7,7 +7,9 @@ pub mod audio_processor;
 
 // Re-export commonly used types
 pub use audio_processor::{
-    AudioError, AudioProcessor, ImportResult, PeakCache, WaveformData, CACHE_DIR, UPLOAD_DIR,
+    content_type_for_format, slice_peak_pyramid, AudioError, AudioProcessor, ExportMode,
+    ExportOptions, ImportResult, Job, JobStatus, LoudnessInfo, PeakCache, WaveformData, CACHE_DIR,
+    UPLOAD_DIR,
 };
 
 #[cfg(test)]
@@ -17,7 +19,7 @@ mod tests {
 
     #[test]
     fn test_audio_processor_initialization() {
-        let processor = AudioProcessor::new();
+        let processor = AudioProcessor::new(4);
         assert!(processor.waveform_cache.lock().unwrap().is_empty());
         assert!(processor.cache.lock().unwrap().is_empty());
     }
@@ -1,22 +1,31 @@
 use actix_cors::Cors;
+use actix_multipart::Multipart;
 use actix_web::{
     error, get, post, web, App, Error, HttpRequest, HttpResponse, HttpServer, Responder, Result,
 };
+use actix_web::http::header;
 use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
-use std::sync::Mutex;
+use std::sync::Arc;
 use std::time::Instant;
 use std::{env, fs};
 
 // Import the audio processing modules
 mod audio_processor;
 use audio_processor::{
-    AudioError, AudioProcessor, ImportResult, PeakCache, WaveformData, CACHE_DIR, UPLOAD_DIR,
+    content_type_for_format, slice_peak_pyramid, AudioError, AudioProcessor, ExportMode,
+    ExportOptions, ImportResult, Job, JobStatus, LoudnessInfo, PeakCache, WaveformData, CACHE_DIR,
+    UPLOAD_DIR,
 };
 
 // Shared state for the application
+//
+// `AudioProcessor` already guards each of its internal maps with its own
+// lock, so polling a job's status or analyzing one file never blocks behind
+// another file's decode.
 struct AppState {
-    audio_processor: Mutex<AudioProcessor>,
+    audio_processor: Arc<AudioProcessor>,
 }
 
 // API response wrapper
@@ -51,10 +60,15 @@ async fn health() -> impl Responder {
     HttpResponse::Ok().json(ApiResponse::success("OK"))
 }
 
+#[derive(Serialize)]
+struct UploadAccepted {
+    job_id: String,
+}
+
 #[post("/api/import")]
 async fn import_audio(
     req: HttpRequest,
-    payload: web::Payload,
+    payload: Multipart,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
     // Get content type
@@ -70,26 +84,73 @@ async fn import_audio(
         )));
     }
 
-    // Process the multipart form
-    let mut processor = data.audio_processor.lock().unwrap();
+    // Save the upload and hand the decode off to a background job
+    let processor = data.audio_processor.clone();
     let result = processor.process_upload(payload).await;
 
     match result {
-        Ok(import_result) => Ok(HttpResponse::Ok().json(ApiResponse::success(import_result))),
+        Ok(job_id) => {
+            Ok(HttpResponse::Accepted().json(ApiResponse::success(UploadAccepted { job_id })))
+        }
         Err(e) => Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e))),
     }
 }
 
+#[get("/api/jobs/{id}")]
+async fn get_job(path: web::Path<String>, data: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    let job_id = path.into_inner();
+
+    match data.audio_processor.get_job(&job_id) {
+        Ok(job) => Ok(HttpResponse::Ok().json(ApiResponse::success(job))),
+        Err(e) => Ok(HttpResponse::NotFound().json(ApiResponse::<()>::error(e))),
+    }
+}
+
+// Optional zoom window/resolution for `GET /api/waveform/{cache_key}`. With
+// none of these set, the full duration at the coarsest pyramid level is
+// returned.
+#[derive(Deserialize)]
+struct WaveformQuery {
+    start: Option<f64>,
+    end: Option<f64>,
+    width: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct WaveformResponse {
+    peaks: Vec<f32>,
+    start: f64,
+    end: f64,
+    duration: f64,
+    sample_rate: u32,
+    format: String,
+}
+
 #[get("/api/waveform/{cache_key}")]
 async fn get_waveform(
     path: web::Path<String>,
+    query: web::Query<WaveformQuery>,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
     let cache_key = path.into_inner();
-    let mut processor = data.audio_processor.lock().unwrap();
-    
-    match processor.get_waveform(&cache_key).await {
-        Ok(waveform) => Ok(HttpResponse::Ok().json(ApiResponse::success(waveform))),
+
+    match data.audio_processor.get_waveform(&cache_key).await {
+        Ok(waveform) => {
+            let start = query.start.unwrap_or(0.0);
+            let end = query.end.unwrap_or(waveform.duration);
+            let width = query.width.unwrap_or(0);
+
+            let peaks = slice_peak_pyramid(&waveform, start, end, width);
+
+            Ok(HttpResponse::Ok().json(ApiResponse::success(WaveformResponse {
+                peaks,
+                start,
+                end,
+                duration: waveform.duration,
+                sample_rate: waveform.sample_rate,
+                format: waveform.format,
+            })))
+        }
         Err(e) => Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e))),
     }
 }
@@ -100,14 +161,160 @@ async fn get_peaks(
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
     let cache_key = path.into_inner();
-    let processor = data.audio_processor.lock().unwrap();
-    
-    match processor.get_peaks(&cache_key).await {
+
+    match data.audio_processor.get_peaks(&cache_key).await {
         Ok(peaks) => Ok(HttpResponse::Ok().json(ApiResponse::success(peaks))),
         Err(e) => Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e))),
     }
 }
 
+// Parse a single `Range: bytes=start-end` header into an inclusive
+// `(start, end)` byte range. A suffix range longer than the file is clamped
+// to the whole file. Returns `None` for multi-range, malformed, or
+// out-of-bounds requests (including an end at or past `file_size`) so the
+// caller can fall back to serving the whole file.
+fn parse_byte_range(header_value: &str, file_size: u64) -> Option<(u64, u64)> {
+    // Bail out before any `file_size - 1` arithmetic below, so an empty file
+    // can't underflow `u64` regardless of which header shape is given.
+    if file_size == 0 {
+        return None;
+    }
+
+    let spec = header_value.strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start_str, end_str) = spec.split_once('-')?;
+
+    let (start, end) = if start_str.is_empty() {
+        // Suffix range, e.g. "bytes=-500" means the last 500 bytes.
+        let suffix_len: u64 = end_str.parse().ok()?;
+        (file_size.saturating_sub(suffix_len), file_size - 1)
+    } else {
+        let start: u64 = start_str.parse().ok()?;
+        let end = if end_str.is_empty() {
+            file_size - 1
+        } else {
+            end_str.parse().ok()?
+        };
+        (start, end)
+    };
+
+    if start > end || end >= file_size {
+        return None;
+    }
+
+    Some((start, end))
+}
+
+// What `read_audio_file` produced, passed back up to the handler so it can
+// build the right response headers.
+enum AudioFileRead {
+    Partial { buf: Vec<u8>, start: u64, end: u64, file_size: u64 },
+    Full { buf: Vec<u8> },
+}
+
+// Open `file_path` and read either the requested byte range or the whole
+// file. This runs on a blocking task (see `get_audio`) since none of it is
+// async I/O.
+fn read_audio_file(file_path: &Path, range_header: Option<String>) -> std::io::Result<AudioFileRead> {
+    let file_size = fs::metadata(file_path)?.len();
+    let mut file = fs::File::open(file_path)?;
+
+    let range = range_header
+        .as_deref()
+        .and_then(|v| parse_byte_range(v, file_size));
+
+    if let Some((start, end)) = range {
+        let mut buf = vec![0u8; (end - start + 1) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut buf)?;
+        Ok(AudioFileRead::Partial { buf, start, end, file_size })
+    } else {
+        let mut buf = Vec::with_capacity(file_size as usize);
+        file.read_to_end(&mut buf)?;
+        Ok(AudioFileRead::Full { buf })
+    }
+}
+
+#[get("/api/audio/{cache_key}")]
+async fn get_audio(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let cache_key = path.into_inner();
+
+    let file_path = data
+        .audio_processor
+        .get_file_path(&cache_key)
+        .map_err(|e| error::ErrorNotFound(e.to_string()))?;
+    let waveform = data
+        .audio_processor
+        .get_waveform(&cache_key)
+        .await
+        .map_err(|e| error::ErrorNotFound(e.to_string()))?;
+
+    let content_type = content_type_for_format(&waveform.format);
+    let range_header = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+
+    // Reading the source file is blocking I/O and can be large; run it on a
+    // blocking task so a big playback request can't stall the async worker
+    // thread other requests (e.g. job-status polling) are sharing.
+    let read = tokio::task::spawn_blocking(move || read_audio_file(&file_path, range_header))
+        .await
+        .map_err(|e| error::ErrorInternalServerError(format!("Read task panicked: {}", e)))??;
+
+    match read {
+        AudioFileRead::Partial { buf, start, end, file_size } => Ok(HttpResponse::PartialContent()
+            .content_type(content_type)
+            .insert_header((header::ACCEPT_RANGES, "bytes"))
+            .insert_header((
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, file_size),
+            ))
+            .body(buf)),
+        AudioFileRead::Full { buf } => Ok(HttpResponse::Ok()
+            .content_type(content_type)
+            .insert_header((header::ACCEPT_RANGES, "bytes"))
+            .body(buf)),
+    }
+}
+
+#[get("/api/loudness/{cache_key}")]
+async fn get_loudness(
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let cache_key = path.into_inner();
+
+    match data.audio_processor.get_loudness(&cache_key).await {
+        Ok(loudness) => Ok(HttpResponse::Ok().json(ApiResponse::success(loudness))),
+        Err(e) => Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e))),
+    }
+}
+
+#[post("/api/export/{cache_key}")]
+async fn export_audio(
+    path: web::Path<String>,
+    options: web::Json<ExportOptions>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let cache_key = path.into_inner();
+
+    let mp3_bytes = data
+        .audio_processor
+        .export_mp3(&cache_key, options.into_inner())
+        .await
+        .map_err(|e| error::ErrorBadRequest(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().content_type("audio/mpeg").body(mp3_bytes))
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize logger
@@ -118,10 +325,12 @@ async fn main() -> std::io::Result<()> {
     fs::create_dir_all(*CACHE_DIR).expect("Failed to create cache directory");
 
     // Initialize audio processor
-    let audio_processor = AudioProcessor::new();
-    let app_state = web::Data::new(AppState {
-        audio_processor: Mutex::new(audio_processor),
-    });
+    let max_concurrent_imports = env::var("MAX_CONCURRENT_IMPORTS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(4);
+    let audio_processor = Arc::new(AudioProcessor::new(max_concurrent_imports));
+    let app_state = web::Data::new(AppState { audio_processor });
 
     // Start the HTTP server
     let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
@@ -144,10 +353,65 @@ async fn main() -> std::io::Result<()> {
             .app_data(app_state.clone())
             .service(health)
             .service(import_audio)
+            .service(get_job)
             .service(get_waveform)
             .service(get_peaks)
+            .service(get_audio)
+            .service(get_loudness)
+            .service(export_audio)
     })
     .bind((host, port))?
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_byte_range_basic() {
+        assert_eq!(parse_byte_range("bytes=0-99", 1000), Some((0, 99)));
+    }
+
+    #[test]
+    fn test_parse_byte_range_open_ended() {
+        assert_eq!(parse_byte_range("bytes=900-", 1000), Some((900, 999)));
+    }
+
+    #[test]
+    fn test_parse_byte_range_suffix() {
+        assert_eq!(parse_byte_range("bytes=-500", 1000), Some((500, 999)));
+    }
+
+    #[test]
+    fn test_parse_byte_range_suffix_larger_than_file() {
+        assert_eq!(parse_byte_range("bytes=-5000", 1000), Some((0, 999)));
+    }
+
+    #[test]
+    fn test_parse_byte_range_rejects_multi_range() {
+        assert_eq!(parse_byte_range("bytes=0-99,200-299", 1000), None);
+    }
+
+    #[test]
+    fn test_parse_byte_range_rejects_out_of_bounds() {
+        assert_eq!(parse_byte_range("bytes=0-1000", 1000), None);
+        assert_eq!(parse_byte_range("bytes=500-100", 1000), None);
+    }
+
+    #[test]
+    fn test_parse_byte_range_rejects_malformed() {
+        assert_eq!(parse_byte_range("0-99", 1000), None);
+        assert_eq!(parse_byte_range("bytes=abc-99", 1000), None);
+    }
+
+    #[test]
+    fn test_parse_byte_range_rejects_empty_file() {
+        // None of these header shapes should panic on the `file_size - 1`
+        // arithmetic when `file_size == 0`.
+        assert_eq!(parse_byte_range("bytes=0-0", 0), None);
+        assert_eq!(parse_byte_range("bytes=-500", 0), None);
+        assert_eq!(parse_byte_range("bytes=0-", 0), None);
+    }
+}